@@ -0,0 +1,88 @@
+use clap::ValueEnum;
+
+/// The order in which held notes are stepped through.
+#[derive(Debug, Default, Clone, Copy, ValueEnum)]
+pub enum ArpOrder {
+    /// Lowest to highest, repeating
+    #[default]
+    Up,
+    /// Highest to lowest, repeating
+    Down,
+    /// Up then back down without repeating the extremes
+    UpDown,
+}
+
+/// Sequences the currently held notes one at a time using a phase accumulator
+/// measured in steps: each sample advances `phase` by `delta_phase`, and every
+/// time `phase` crosses an integer boundary we move to the next note.
+pub struct Arp {
+    /// steps advanced per sample (`rate_hz / sample_rate`)
+    delta_phase: f32,
+    /// fraction of each step for which the note sounds before being detached
+    gate: f32,
+    order: ArpOrder,
+
+    /// position within the current step, in `[0, 1)`
+    phase: f32,
+    /// index into the ordered note sequence of the current step
+    index: usize,
+}
+
+impl Arp {
+    pub fn new(sample_rate: f32, bpm: f32, steps_per_beat: f32, gate: f32, order: ArpOrder) -> Arp {
+        let rate_hz = bpm / 60.0 * steps_per_beat;
+        Arp {
+            delta_phase: rate_hz / sample_rate,
+            gate: gate.clamp(0.0, 1.0),
+            order,
+            phase: 0.0,
+            index: 0,
+        }
+    }
+
+    /// Build the ordered sequence of notes to step through from the sorted set
+    /// of held notes.
+    fn sequence(&self, sorted: &[i8]) -> Vec<i8> {
+        match self.order {
+            ArpOrder::Up => sorted.to_vec(),
+            ArpOrder::Down => sorted.iter().rev().copied().collect(),
+            ArpOrder::UpDown if sorted.len() > 2 => sorted
+                .iter()
+                .chain(sorted[1..sorted.len() - 1].iter().rev())
+                .copied()
+                .collect(),
+            ArpOrder::UpDown => sorted.to_vec(),
+        }
+    }
+
+    /// Advance the arpeggiator across a buffer, returning for each sample which
+    /// note (if any) should sound. `None` marks the gap after a step's gate has
+    /// closed, so consecutive notes are detached rather than legato.
+    pub fn schedule(&mut self, sorted: &[i8], buf_len: usize) -> Vec<Option<i8>> {
+        let sequence = self.sequence(sorted);
+        if sequence.is_empty() {
+            self.phase = 0.0;
+            self.index = 0;
+            return vec![None; buf_len];
+        }
+
+        let mut schedule = Vec::with_capacity(buf_len);
+        for _ in 0..buf_len {
+            if self.phase >= 1.0 {
+                self.phase -= 1.0;
+                self.index = (self.index + 1) % sequence.len();
+            }
+
+            let note = if self.phase <= self.gate {
+                Some(sequence[self.index % sequence.len()])
+            } else {
+                None
+            };
+            schedule.push(note);
+
+            self.phase += self.delta_phase;
+        }
+
+        schedule
+    }
+}
@@ -0,0 +1,119 @@
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use midir::{MidiInput, MidiInputConnection};
+
+use crate::MidiNote;
+
+/// Where note events come from. The keyboard path polls `device_query` for
+/// keycodes (see `audio_loop`), while `Midi` listens to a real MIDI controller.
+#[derive(Debug, Clone)]
+pub enum Input {
+    /// Drive notes from the computer keyboard via `device_query`
+    Keyboard,
+    /// Drive notes from a MIDI input device, optionally selecting a port by
+    /// (sub)string match against its name
+    Midi(Option<String>),
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input::Keyboard
+    }
+}
+
+impl FromStr for Input {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Input> {
+        match s.split_once(':') {
+            Some(("midi", port)) => Ok(Input::Midi(Some(port.to_string()))),
+            None if s == "midi" => Ok(Input::Midi(None)),
+            None if s == "keyboard" => Ok(Input::Keyboard),
+            _ => Err(anyhow!("unknown input source: {}", s)),
+        }
+    }
+}
+
+/// Shared list of currently held MIDI notes and their velocities, written to by
+/// the MIDI callback and read by the audio thread via `Notes::update_midi`.
+pub type MidiNotes = Arc<Mutex<Vec<(MidiNote, u8)>>>;
+
+/// Open a MIDI input connection and feed note on/off events into `notes`.
+///
+/// The returned connection must be kept alive for as long as input is desired;
+/// dropping it closes the port. Running status is honoured, so note events that
+/// omit the repeated status byte are still parsed correctly.
+pub fn connect(port: &Option<String>, notes: MidiNotes) -> Result<MidiInputConnection<()>> {
+    let midi_in = MidiInput::new("keyboard-music")?;
+    let ports = midi_in.ports();
+    let chosen = match port {
+        Some(name) => ports
+            .iter()
+            .find(|p| {
+                midi_in
+                    .port_name(p)
+                    .map(|n| n.contains(name))
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| anyhow!("no MIDI input port matching {:?}", name))?,
+        None => ports
+            .first()
+            .ok_or(anyhow!("no MIDI input ports available"))?,
+    };
+
+    // status byte of the last channel message, reused for running status
+    let mut running_status: Option<u8> = None;
+    let connection = midi_in
+        .connect(
+            chosen,
+            "keyboard-music",
+            move |_timestamp, message, _| {
+                handle_message(message, &mut running_status, &notes);
+            },
+            (),
+        )
+        .map_err(|e| anyhow!("failed to connect to MIDI port: {}", e))?;
+
+    Ok(connection)
+}
+
+/// Parse a single MIDI message and update the held-note list. Only Note-On
+/// (`0x90`) and Note-Off (`0x80`) are acted upon; a Note-On with zero velocity
+/// is treated as a Note-Off, as the MIDI spec allows.
+fn handle_message(message: &[u8], running_status: &mut Option<u8>, notes: &MidiNotes) {
+    // running status: messages may reuse the previous channel status byte and
+    // start straight at the data bytes
+    let (status, data) = match message.split_first() {
+        Some((&first, rest)) if first & 0x80 != 0 => {
+            // channel messages set the running status, system common messages
+            // cancel it and real-time messages leave it alone
+            match first {
+                0x80..=0xef => *running_status = Some(first),
+                0xf0..=0xf7 => *running_status = None,
+                _ => {}
+            }
+            (first, rest)
+        }
+        Some(_) => match *running_status {
+            Some(status) => (status, message),
+            None => return,
+        },
+        None => return,
+    };
+
+    match status & 0xf0 {
+        0x90 if data.len() >= 2 && data[1] > 0 => {
+            let (note, velocity) = (data[0], data[1]);
+            let mut notes = notes.lock().unwrap();
+            notes.retain(|(n, _)| *n != note);
+            notes.push((note, velocity));
+        }
+        0x80 | 0x90 if data.len() >= 2 => {
+            let note = data[0];
+            notes.lock().unwrap().retain(|(n, _)| *n != note);
+        }
+        _ => {}
+    }
+}
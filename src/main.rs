@@ -1,7 +1,11 @@
 #![recursion_limit = "256"]
 
+mod arp;
+mod input;
 mod keymap;
 mod notes;
+mod record;
+mod record_midi;
 mod stream;
 #[cfg(feature = "visualiser")]
 mod vis;
@@ -15,6 +19,7 @@ use anyhow::{anyhow, Result};
 use clap::Parser;
 use cpal::traits::{DeviceTrait, HostTrait};
 use device_query::{DeviceQuery, DeviceState};
+use input::Input;
 use keymap::KeyMap;
 use notes::Notes;
 use stream::StreamWrapper;
@@ -22,6 +27,14 @@ use wave::Wave;
 
 pub type MidiNote = u8;
 
+/// A chunk of interleaved samples tagged with the sample-clock position of its
+/// first sample, so the visualiser can tell how recent each buffer is and drop
+/// stale backlog when redraws fall behind the audio.
+pub struct AudioChunk {
+    pub seq: u64,
+    pub samples: Vec<f32>,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum Action {
     NextWave,
@@ -47,11 +60,119 @@ pub struct Args {
     /// Choose which type of wave to play
     #[clap(short = 'w', long = "wave", value_enum, default_value_t = Wave::default())]
     pub wave: Wave,
+
+    /// FM modulator-to-carrier frequency ratios, deepest modulator first; pass
+    /// up to three for a 2-4 operator chain (only used by `--wave fm`)
+    #[clap(long = "fm-ratio", value_delimiter = ',', default_value = "2.0")]
+    pub fm_ratio: Vec<f32>,
+
+    /// FM modulation indices paired with `--fm-ratio`: how strongly each
+    /// modulator bends the next operator in the chain
+    #[clap(long = "fm-mod-index", value_delimiter = ',', default_value = "1.0")]
+    pub fm_mod_index: Vec<f32>,
+
+    /// Play a sampled instrument from this SoundFont (SF2) file instead of an
+    /// oscillator wave
+    #[clap(long = "soundfont", value_name = "FILE")]
+    pub soundfont: Option<std::path::PathBuf>,
+
+    /// Which preset number to use from the SoundFont
+    #[clap(long = "preset", default_value_t = 0)]
+    pub preset: u16,
+
+    /// Master output gain applied before soft-clipping
+    #[clap(long = "master-volume", default_value_t = 1.0)]
+    pub master_volume: f32,
+
+    /// Use band-limited (PolyBLEP) square/sawtooth oscillators to reduce aliasing
+    #[clap(long = "band-limited")]
+    pub band_limited: bool,
+
+    /// Where to read notes from: "keyboard" or "midi[:port]" to use a MIDI
+    /// controller (the optional port is matched against its name)
+    #[clap(short = 'i', long = "input", default_value = "keyboard")]
+    pub input: Input,
+
+    /// Envelope attack time in milliseconds (0 -> full volume)
+    #[clap(long = "attack", default_value_t = 5.0)]
+    pub attack: f32,
+
+    /// Envelope decay time in milliseconds (full volume -> sustain level)
+    #[clap(long = "decay", default_value_t = 30.0)]
+    pub decay: f32,
+
+    /// Envelope sustain level while a note is held (0-1)
+    #[clap(long = "sustain", default_value_t = 0.8)]
+    pub sustain: f32,
+
+    /// Envelope release time in milliseconds (current level -> silence)
+    #[clap(long = "release", default_value_t = 60.0)]
+    pub release: f32,
+
+    /// Shape of the envelope ramps: linear or exponential
+    #[clap(long = "envelope-curve", value_enum, default_value_t = notes::EnvCurve::default())]
+    pub envelope_curve: notes::EnvCurve,
+
+    /// Record the generated audio to a WAV file at this path
+    #[clap(long = "record", value_name = "FILE")]
+    pub record: Option<std::path::PathBuf>,
+
+    /// Sample format for `--record`: 16-bit PCM or 32-bit float
+    #[clap(long = "record-format", value_enum, default_value_t = record::WavFormat::default())]
+    pub record_format: record::WavFormat,
+
+    /// Record played notes to a Standard MIDI File at this path
+    #[clap(long = "record-midi", value_name = "FILE")]
+    pub record_midi: Option<std::path::PathBuf>,
+
+    /// Sequence held notes one at a time instead of sounding them together
+    #[clap(long = "arp")]
+    pub arp: bool,
+
+    /// Arpeggiator tempo in beats per minute
+    #[clap(long = "arp-bpm", default_value_t = 120.0)]
+    pub arp_bpm: f32,
+
+    /// Arpeggiator steps per beat (e.g. 4 for sixteenth notes)
+    #[clap(long = "arp-steps", default_value_t = 4.0)]
+    pub arp_steps: f32,
+
+    /// Fraction of each arpeggiator step a note sounds for (0-1)
+    #[clap(long = "arp-gate", default_value_t = 0.5)]
+    pub arp_gate: f32,
+
+    /// Order in which the arpeggiator steps through held notes
+    #[clap(long = "arp-order", value_enum, default_value_t = arp::ArpOrder::default())]
+    pub arp_order: arp::ArpOrder,
+
+    /// List the available output devices and exit
+    #[clap(long = "list-devices")]
+    pub list_devices: bool,
+
+    /// Output device to play through, matched against its name (defaults to the
+    /// host's default output)
+    #[clap(long = "device", value_name = "NAME")]
+    pub device: Option<String>,
+
+    /// Output sample rate in Hz; must be supported by the chosen device
+    /// (defaults to the device's default)
+    #[clap(long = "sample-rate", value_name = "HZ")]
+    pub sample_rate: Option<u32>,
+
+    /// Output buffer size in frames; must fall within the device's supported
+    /// range (defaults to the device's own buffering)
+    #[clap(long = "buffer-size", value_name = "FRAMES")]
+    pub buffer_size: Option<u32>,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
+    if args.list_devices {
+        list_devices()?;
+        return Ok(());
+    }
+
     #[cfg(feature = "visualiser")]
     {
         use std::sync::mpsc;
@@ -75,7 +196,7 @@ fn main() -> Result<()> {
 
 fn audio_loop(
     args: Args,
-    audio_tx: Option<Sender<Vec<f32>>>,
+    audio_tx: Option<Sender<AudioChunk>>,
     option_rx: Option<Receiver<Action>>,
 ) -> Result<()> {
     #[cfg(not(feature = "visualiser"))]
@@ -86,26 +207,108 @@ fn audio_loop(
 
     // audio setup
     let host = cpal::default_host();
-    let device = host
-        .default_output_device()
-        .ok_or(anyhow!("No output device available"))?;
-    let config = device.default_output_config()?;
-    let sample_rate = config.sample_rate().0 as f32;
+    let device = match &args.device {
+        Some(name) => pick_device(&host, name)?,
+        None => host
+            .default_output_device()
+            .ok_or(anyhow!("No output device available"))?,
+    };
+    let config = build_stream_config(&device, args.sample_rate, args.buffer_size)?;
+    let sample_rate = config.sample_rate.0 as f32;
+    let channels = config.channels;
 
     // shared data (audio thread + keyboard thread) of which keycodes are currently active
     let active_keys = Arc::new(Mutex::new(Vec::<MidiNote>::new()));
-    let notes = Arc::new(Mutex::new(Notes::new(
+    // shared data (audio thread + MIDI callback) of which MIDI notes are held
+    let midi_notes: input::MidiNotes = Arc::new(Mutex::new(Vec::new()));
+    let adsr = notes::Adsr::new(
+        sample_rate,
+        args.attack,
+        args.decay,
+        args.sustain,
+        args.release,
+        args.envelope_curve,
+    );
+    let fm = wave::FmConfig {
+        ratios: args.fm_ratio.clone(),
+        mod_indices: args.fm_mod_index.clone(),
+    };
+    // if a soundfont was given, load it as the sample-based generator
+    let soundfont = match &args.soundfont {
+        Some(path) => Some(Box::new(wave::soundfont::SoundFont::load(
+            path,
+            args.preset,
+            sample_rate,
+        )?) as Box<dyn wave::WaveGenerator>),
+        None => None,
+    };
+    let mut notes = Notes::new(
         &args.keymap,
         args.wave,
         sample_rate,
-    )?));
+        adsr,
+        fm,
+        args.band_limited,
+        soundfont,
+        args.master_volume,
+    )?;
+
+    // if requested, enable the arpeggiator
+    if args.arp {
+        notes.set_arp(arp::Arp::new(
+            sample_rate,
+            args.arp_bpm,
+            args.arp_steps,
+            args.arp_gate,
+            args.arp_order,
+        ));
+    }
+
+    // if requested, open a MIDI-file recorder and route note transitions to it;
+    // it owns a writer thread and must be kept alive for the recording
+    let _midi_recorder = match &args.record_midi {
+        Some(path) => {
+            let recorder = record_midi::MidiRecorder::new(path)?;
+            notes.record_midi_to(recorder.sender());
+            Some(recorder)
+        }
+        None => None,
+    };
+
+    let notes = Arc::new(Mutex::new(notes));
+
+    // if requested, open a MIDI input connection; it feeds `midi_notes` and must
+    // be kept alive for as long as we want to receive events
+    let _midi_connection = match &args.input {
+        Input::Keyboard => None,
+        Input::Midi(port) => Some(input::connect(port, midi_notes.clone())?),
+    };
+    let using_midi = matches!(args.input, Input::Midi(_));
+
+    // if requested, open a WAV recorder; it owns a writer thread and must be
+    // kept alive for the duration of the recording
+    let recorder = match &args.record {
+        Some(path) => Some(record::WavRecorder::new(
+            path,
+            sample_rate as u32,
+            channels,
+            args.record_format,
+        )?),
+        None => None,
+    };
 
     // create audio stream and start playing it immediately
     let mut stream = StreamWrapper::new(device.build_output_stream(
-        &config.into(),
+        &config,
         {
             let notes = notes.clone();
             let active_keys = active_keys.clone();
+            let midi_notes = midi_notes.clone();
+            let record_tx = recorder.as_ref().map(|r| r.sender());
+            // running count of samples produced, stamped onto each visualiser
+            // chunk so it can realign its window to the newest audio
+            #[cfg(feature = "visualiser")]
+            let mut sample_clock: u64 = 0;
             move |buf: &mut [f32], _: &cpal::OutputCallbackInfo| {
                 // this buffer isn't zeroed on all platforms
                 buf.fill(0.0);
@@ -115,16 +318,28 @@ fn audio_loop(
                     let mut notes = notes.lock().unwrap();
 
                     {
-                        notes.update_keys(&*active_keys.lock().unwrap());
+                        if using_midi {
+                            notes.update_midi(&midi_notes.lock().unwrap());
+                        } else {
+                            notes.update_keys(&*active_keys.lock().unwrap());
+                        }
                     }
 
                     notes.generate_audio(buf);
                 }
 
-                // send a copy of the audio buffer over to the visualiser
+                // tee the buffer off to the WAV recorder
+                if let Some(ref tx) = record_tx {
+                    let _ = tx.send(buf.to_vec());
+                }
+
+                // send a timestamped copy of the audio buffer to the visualiser
                 #[cfg(feature = "visualiser")]
                 if let Some(ref tx) = audio_tx {
-                    let _ = tx.send(buf.to_vec());
+                    let samples = buf.to_vec();
+                    let seq = sample_clock;
+                    sample_clock += samples.len() as u64;
+                    let _ = tx.send(AudioChunk { seq, samples });
                 }
             }
         },
@@ -139,8 +354,15 @@ fn audio_loop(
         // query key state
         let keys = device_state.get_keys();
 
+        // whether anything is currently being played (keyboard or MIDI)
+        let is_active = if using_midi {
+            !midi_notes.lock().unwrap().is_empty()
+        } else {
+            keys.len() > 0
+        };
+
         // check if we should pause the stream due to inactivity
-        if keys.len() > 0 {
+        if is_active {
             last_key_press = Instant::now();
             if stream.is_paused() {
                 stream.play()?;
@@ -150,7 +372,7 @@ fn audio_loop(
         }
 
         // acquire lock and update active keys so the audio thread can respond to it
-        {
+        if !using_midi {
             let mut active_keys = active_keys.lock().unwrap();
             active_keys.drain(..);
             active_keys.extend(keys.iter().map(|k| *k as MidiNote));
@@ -168,3 +390,57 @@ fn audio_loop(
         std::thread::sleep(KEYPRESS_INTERVAL);
     }
 }
+
+/// Print every output device the default host exposes, marking the default one.
+fn list_devices() -> Result<()> {
+    let host = cpal::default_host();
+    let default = host.default_output_device().map(|d| d.name().unwrap_or_default());
+    println!("Available output devices:");
+    for device in host.output_devices()? {
+        let name = device.name().unwrap_or_else(|_| "<unknown>".to_string());
+        let marker = if Some(&name) == default.as_ref() { " (default)" } else { "" };
+        println!("  {}{}", name, marker);
+    }
+    Ok(())
+}
+
+/// Find an output device whose name contains `needle` (case-insensitive).
+fn pick_device(host: &cpal::Host, needle: &str) -> Result<cpal::Device> {
+    let needle = needle.to_lowercase();
+    host.output_devices()?
+        .find(|d| {
+            d.name()
+                .map(|n| n.to_lowercase().contains(&needle))
+                .unwrap_or(false)
+        })
+        .ok_or_else(|| anyhow!("No output device matching {:?}", needle))
+}
+
+/// Build a stream config for `device`, honouring an explicit sample rate and
+/// buffer size when given and otherwise falling back to the device's defaults.
+fn build_stream_config(
+    device: &cpal::Device,
+    sample_rate: Option<u32>,
+    buffer_size: Option<u32>,
+) -> Result<cpal::StreamConfig> {
+    use cpal::{BufferSize, SampleRate};
+
+    let default = device.default_output_config()?;
+    let mut config: cpal::StreamConfig = default.clone().into();
+
+    // if a sample rate was requested, find a supported range that covers it
+    if let Some(rate) = sample_rate {
+        let supported = device
+            .supported_output_configs()?
+            .find(|c| c.min_sample_rate().0 <= rate && rate <= c.max_sample_rate().0)
+            .ok_or_else(|| anyhow!("Sample rate {} Hz not supported by device", rate))?;
+        config.channels = supported.channels();
+        config.sample_rate = SampleRate(rate);
+    }
+
+    if let Some(frames) = buffer_size {
+        config.buffer_size = BufferSize::Fixed(frames);
+    }
+
+    Ok(config)
+}
@@ -1,16 +1,19 @@
 use std::collections::HashMap;
+use std::sync::mpsc::Sender;
 
 use anyhow::Result;
 
+use crate::arp::Arp;
 use crate::keymap::{self, KeyMap, KeyToNote, NoteToKey};
-use crate::wave::{Wave, WaveGenerator};
+use crate::record_midi::MidiEvent;
+use crate::wave::{FmConfig, Wave, WaveGenerator};
 
 /// Max audio volume used when generating the audio data
 pub const MAX_VOLUME: f32 = 0.5;
 /// Middle "A" - all note frequency calculations are relative to this
 pub const BASE_NOTE_FREQ: f32 = 440.0;
 /// Midi pitch number of `BASE_NOTE_FREQ`, must be kept in sync with it
-const MIDI_OFFSET: i8 = 69;
+pub const MIDI_OFFSET: i8 = 69;
 
 pub struct Notes {
     sample_rate: f32,
@@ -21,28 +24,96 @@ pub struct Notes {
 
     wave: Wave,
     wave_generator: Box<dyn WaveGenerator>,
+    fm: FmConfig,
+    /// whether to use the PolyBLEP band-limited square/sawtooth generators
+    band_limited: bool,
+    /// when a soundfont is loaded it replaces the oscillator and `update_wave`
+    /// leaves it in place
+    soundfont_active: bool,
+
+    adsr: Adsr,
+
+    /// optional sink for note on/off transitions, used to record a MIDI file
+    midi_tx: Option<Sender<MidiEvent>>,
+
+    /// optional arpeggiator that sequences held notes instead of stacking them
+    arp: Option<Arp>,
+
+    /// 0-127 velocity -> amplitude table (perceptual loudness curve)
+    velocity_curve: [f32; 128],
+    /// overall output gain applied before soft-clipping
+    master_volume: f32,
 }
 
 impl Notes {
-    pub fn new(keymap: &KeyMap, wave: Wave, sample_rate: f32) -> Result<Notes> {
+    pub fn new(
+        keymap: &KeyMap,
+        wave: Wave,
+        sample_rate: f32,
+        adsr: Adsr,
+        fm: FmConfig,
+        band_limited: bool,
+        soundfont: Option<Box<dyn WaveGenerator>>,
+        master_volume: f32,
+    ) -> Result<Notes> {
         let (key_to_note, note_to_key) = keymap::generate_maps(keymap)?;
+        let soundfont_active = soundfont.is_some();
+        let wave_generator =
+            soundfont.unwrap_or_else(|| wave.generator(sample_rate, &fm, band_limited));
         Ok(Notes {
             sample_rate,
             notes: HashMap::new(),
             note_to_key,
             key_to_note,
             wave,
-            wave_generator: wave.generator(sample_rate),
+            wave_generator,
+            fm,
+            band_limited,
+            soundfont_active,
+            adsr,
+            midi_tx: None,
+            arp: None,
+            velocity_curve: velocity_curve(),
+            master_volume,
         })
     }
 
+    /// Route note on/off transitions to the given channel so they can be
+    /// serialised to a Standard MIDI File.
+    pub fn record_midi_to(&mut self, tx: Sender<MidiEvent>) {
+        self.midi_tx = Some(tx);
+    }
+
+    /// Enable arpeggiator mode, sequencing held notes one at a time.
+    pub fn set_arp(&mut self, arp: Arp) {
+        self.arp = Some(arp);
+    }
+
+    /// Log a single note transition to the MIDI recorder, if one is attached.
+    fn emit_midi(&self, on: bool, rel_midi_note: i8, velocity: u8) {
+        if let Some(tx) = &self.midi_tx {
+            let note = (rel_midi_note + MIDI_OFFSET) as u8;
+            let _ = tx.send(MidiEvent::now(on, note, velocity));
+        }
+    }
+
     #[allow(unused)]
     pub fn update_wave(&mut self) {
+        // a loaded soundfont takes precedence over the oscillator waves
+        if self.soundfont_active {
+            return;
+        }
         self.wave = self.wave.next();
-        self.wave_generator = self.wave.generator(self.sample_rate);
+        self.wave_generator = self
+            .wave
+            .generator(self.sample_rate, &self.fm, self.band_limited);
     }
 
     pub fn update_keys(&mut self, active_keys: &[u8]) {
+        // note transitions observed this update, emitted to the MIDI recorder
+        // after the borrows of `self.notes` below are released
+        let mut transitions: Vec<(bool, i8, u8)> = Vec::new();
+
         // flag any notes that should no longer be playing as inactive
         self.notes.retain(|rel_midi_note, state| {
             // are any keys that map to this note still playing?
@@ -50,10 +121,12 @@ impl Notes {
                 .iter()
                 .any(|keycode| active_keys.contains(keycode));
 
-            state.active = is_still_active;
+            if !is_still_active && state.release() {
+                transitions.push((false, *rel_midi_note, state.velocity));
+            }
 
-            // tell the wave to drop its state for any active notes it's keeping track of
-            let should_keep = state.active || state.volume > 0.0;
+            // tell the wave to drop its state only once the release has faded out
+            let should_keep = !state.is_finished();
             if !should_keep {
                 self.wave_generator.clear(*rel_midi_note);
             }
@@ -64,63 +137,325 @@ impl Notes {
         // start playing any new notes, or update existing ones
         for k in active_keys.iter() {
             if let Some(note) = self.key_to_note[*k as usize] {
+                let rel = note as i8 - MIDI_OFFSET;
                 self.notes
-                    .entry(note as i8 - MIDI_OFFSET)
-                    .and_modify(|state| state.active = true)
-                    .or_insert(NoteState::new());
+                    .entry(rel)
+                    .and_modify(|state| {
+                        if state.trigger() {
+                            transitions.push((true, rel, state.velocity));
+                        }
+                    })
+                    .or_insert_with(|| {
+                        transitions.push((true, rel, MAX_VELOCITY));
+                        NoteState::new()
+                    });
+            }
+        }
+
+        for (on, rel, velocity) in transitions {
+            self.emit_midi(on, rel, velocity);
+        }
+    }
+
+    /// Update the playing notes from a list of held MIDI notes and their
+    /// velocities. This mirrors `update_keys` but keys straight off MIDI note
+    /// numbers, so a real controller can drive the same note/wave pipeline
+    /// without going through the computer keyboard's keymap.
+    pub fn update_midi(&mut self, pressed: &[(u8, u8)]) {
+        let mut transitions: Vec<(bool, i8, u8)> = Vec::new();
+
+        self.notes.retain(|rel_midi_note, state| {
+            let midi_note = (*rel_midi_note + MIDI_OFFSET) as u8;
+            let is_still_active = pressed.iter().any(|(note, _)| *note == midi_note);
+
+            if !is_still_active && state.release() {
+                transitions.push((false, *rel_midi_note, state.velocity));
+            }
+
+            let should_keep = !state.is_finished();
+            if !should_keep {
+                self.wave_generator.clear(*rel_midi_note);
             }
+
+            should_keep
+        });
+
+        for (note, velocity) in pressed.iter() {
+            let rel = *note as i8 - MIDI_OFFSET;
+            self.notes
+                .entry(rel)
+                .and_modify(|state| {
+                    if state.trigger() {
+                        transitions.push((true, rel, *velocity));
+                    }
+                    state.velocity = *velocity;
+                })
+                .or_insert_with(|| {
+                    transitions.push((true, rel, *velocity));
+                    NoteState::with_velocity(*velocity)
+                });
+        }
+
+        for (on, rel, velocity) in transitions {
+            self.emit_midi(on, rel, velocity);
         }
     }
 
     pub fn generate_audio(&mut self, buf: &mut [f32]) {
-        let note_volume_ratio = MAX_VOLUME / self.notes.len() as f32;
+        // in arpeggiator mode only one held note sounds at a time, so work out
+        // the per-sample schedule up front; otherwise every note sounds at once
+        let schedule = self.arp.as_mut().map(|arp| {
+            let mut sorted: Vec<i8> = self.notes.keys().copied().collect();
+            sorted.sort_unstable();
+            arp.schedule(&sorted, buf.len())
+        });
+
         for (rel_midi_note, note_state) in self.notes.iter_mut() {
-            // the volume this note should be fading towards
-            let target_volume = note_state.get_target_volume(note_volume_ratio);
+            // perceptual amplitude for this note's velocity; notes now stack at
+            // full level and the soft-clip below keeps the summed buffer in
+            // range, so chords stay even instead of being scaled down linearly
+            let gain = MAX_VOLUME * self.velocity_curve[note_state.velocity.min(MAX_VELOCITY) as usize];
 
             // update audio buffer with this note's wave
             let buf_len = buf.len() as f32;
             self.wave_generator.before(*rel_midi_note);
             for (idx, sample) in buf.iter_mut().enumerate() {
-                let idx = idx as f32;
-                let wave = self.wave_generator.next(idx);
-                *sample += wave * note_state.get_volume(idx / buf_len, target_volume)
+                let wave = self.wave_generator.next(idx as f32);
+                let env = note_state.envelope.next(&self.adsr);
+                // when arpeggiating, only emit this note while it's the current
+                // step and its gate is still open
+                let stepped = match &schedule {
+                    Some(schedule) => schedule[idx] == Some(*rel_midi_note),
+                    None => true,
+                };
+                if stepped {
+                    *sample += wave * gain * env;
+                }
             }
             self.wave_generator.after(buf_len);
+        }
 
-            // set the note to its target volume
-            note_state.set_volume(target_volume);
+        // apply master gain and soft-clip the summed buffer so stacked notes
+        // saturate gently rather than clipping hard
+        for sample in buf.iter_mut() {
+            *sample = soft_clip(*sample * self.master_volume);
         }
     }
 }
 
+/// Soft-clipping with a `tanh` curve: transparent at low levels, compressing
+/// smoothly as the signal approaches full scale.
+#[inline]
+fn soft_clip(sample: f32) -> f32 {
+    sample.tanh()
+}
+
+/// Build a 0-127 velocity -> amplitude table following a roughly logarithmic
+/// curve, so low velocities rise quickly and high ones compress, matching the
+/// perceptual loudness response of classic synths.
+fn velocity_curve() -> [f32; 128] {
+    const CURVE: f32 = 10.0;
+    let mut table = [0.0; 128];
+    let denom = (1.0 + CURVE).ln();
+    for (v, amp) in table.iter_mut().enumerate() {
+        *amp = (1.0 + CURVE * (v as f32 / MAX_VELOCITY as f32)).ln() / denom;
+    }
+    table
+}
+
+/// Full-scale MIDI velocity, used for notes driven from the computer keyboard
+/// which has no velocity information.
+const MAX_VELOCITY: u8 = 127;
+
 struct NoteState {
-    active: bool,
-    volume: f32,
+    velocity: u8,
+    envelope: Envelope,
 }
 
 impl NoteState {
     fn new() -> Self {
+        Self::with_velocity(MAX_VELOCITY)
+    }
+
+    fn with_velocity(velocity: u8) -> Self {
         Self {
-            active: true,
-            volume: 0.0,
+            velocity,
+            envelope: Envelope::new(),
         }
     }
 
-    fn set_volume(&mut self, target_volume: f32) {
-        self.volume = target_volume;
+    /// (Re)start the note: restart the envelope's attack if it had already begun
+    /// releasing, otherwise leave the in-progress envelope alone. Returns `true`
+    /// on a note-on transition.
+    fn trigger(&mut self) -> bool {
+        self.envelope.trigger()
     }
 
-    fn get_volume(&self, t: f32, target_volume: f32) -> f32 {
-        lerp(self.volume, target_volume, t * 3.0)
+    /// Begin the release phase; no-op if already releasing or finished. Returns
+    /// `true` on a note-off transition.
+    fn release(&mut self) -> bool {
+        self.envelope.release()
     }
 
-    fn get_target_volume(&self, volume_ratio: f32) -> f32 {
-        if self.active {
-            volume_ratio
+    /// Whether the note has fully released and can be dropped.
+    fn is_finished(&self) -> bool {
+        self.envelope.is_finished()
+    }
+}
+
+/// Shape of the per-stage ramps. Linear ramps are cheap and predictable;
+/// exponential ramps track perceived loudness more closely and sound more
+/// natural for plucky/percussive patches.
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum)]
+pub enum EnvCurve {
+    #[default]
+    Linear,
+    Exponential,
+}
+
+impl EnvCurve {
+    /// Reshape a normalized `0..=1` ramp position. The rising attack uses an
+    /// ease-out so it reaches full gain quickly, while the falling decay and
+    /// release use an ease-in so they linger near the top and fall off fast.
+    #[inline]
+    fn shape(self, t: f32, rising: bool) -> f32 {
+        match self {
+            EnvCurve::Linear => t,
+            EnvCurve::Exponential if rising => t * (2.0 - t),
+            EnvCurve::Exponential => t * t,
+        }
+    }
+}
+
+/// ADSR envelope timings, precomputed into samples for the configured sample
+/// rate so the per-sample ramp in `generate_audio` stays cheap.
+#[derive(Debug, Clone, Copy)]
+pub struct Adsr {
+    attack_samples: f32,
+    decay_samples: f32,
+    sustain_level: f32,
+    release_samples: f32,
+    curve: EnvCurve,
+}
+
+impl Adsr {
+    pub fn new(
+        sample_rate: f32,
+        attack_ms: f32,
+        decay_ms: f32,
+        sustain_level: f32,
+        release_ms: f32,
+        curve: EnvCurve,
+    ) -> Adsr {
+        // never zero samples, so a ramp always takes at least one step
+        let to_samples = |ms: f32| (ms / 1000.0 * sample_rate).max(1.0);
+        Adsr {
+            attack_samples: to_samples(attack_ms),
+            decay_samples: to_samples(decay_ms),
+            sustain_level: sustain_level.clamp(0.0, 1.0),
+            release_samples: to_samples(release_ms),
+            curve,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Done,
+}
+
+/// Per-note envelope generator. It advances one sample at a time and tracks the
+/// elapsed samples within the current stage, so fades are time-accurate and
+/// independent of the audio buffer length.
+struct Envelope {
+    stage: Stage,
+    elapsed: f32,
+    level: f32,
+    /// the level at the instant release began, so we ramp from wherever we were
+    release_from: f32,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Envelope {
+            stage: Stage::Attack,
+            elapsed: 0.0,
+            level: 0.0,
+            release_from: 0.0,
+        }
+    }
+
+    /// Returns `true` if this actually restarted the attack (i.e. the note was
+    /// releasing or finished), which is a note-on transition for recording.
+    fn trigger(&mut self) -> bool {
+        if matches!(self.stage, Stage::Release | Stage::Done) {
+            self.stage = Stage::Attack;
+            self.elapsed = 0.0;
+            true
         } else {
-            0.0
+            false
+        }
+    }
+
+    /// Returns `true` if this actually began a release (a note-off transition).
+    fn release(&mut self) -> bool {
+        if !matches!(self.stage, Stage::Release | Stage::Done) {
+            self.stage = Stage::Release;
+            self.release_from = self.level;
+            self.elapsed = 0.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.stage == Stage::Done
+    }
+
+    /// Advance the envelope by one sample and return the new gain.
+    fn next(&mut self, adsr: &Adsr) -> f32 {
+        match self.stage {
+            Stage::Attack => {
+                self.elapsed += 1.0;
+                let t = (self.elapsed / adsr.attack_samples).min(1.0);
+                self.level = adsr.curve.shape(t, true);
+                if self.elapsed >= adsr.attack_samples {
+                    self.stage = Stage::Decay;
+                    self.elapsed = 0.0;
+                }
+            }
+            Stage::Decay => {
+                self.elapsed += 1.0;
+                let t = adsr.curve.shape((self.elapsed / adsr.decay_samples).min(1.0), false);
+                self.level = lerp(1.0, adsr.sustain_level, t);
+                if self.elapsed >= adsr.decay_samples {
+                    self.stage = Stage::Sustain;
+                    self.elapsed = 0.0;
+                }
+            }
+            Stage::Sustain => {
+                self.level = adsr.sustain_level;
+            }
+            Stage::Release => {
+                self.elapsed += 1.0;
+                let t = adsr.curve.shape((self.elapsed / adsr.release_samples).min(1.0), false);
+                self.level = lerp(self.release_from, 0.0, t);
+                if self.elapsed >= adsr.release_samples {
+                    self.stage = Stage::Done;
+                    self.level = 0.0;
+                }
+            }
+            Stage::Done => {
+                self.level = 0.0;
+            }
         }
+
+        self.level
     }
 }
 
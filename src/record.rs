@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+use anyhow::Result;
+
+/// Sample format the captured audio is written as.
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum)]
+pub enum WavFormat {
+    /// 16-bit signed PCM (smaller files, the usual choice)
+    #[default]
+    Pcm16,
+    /// 32-bit IEEE float, preserving the synth's full range without clipping
+    Float32,
+}
+
+impl WavFormat {
+    /// WAV `fmt ` format tag: 1 for integer PCM, 3 for IEEE float.
+    fn tag(self) -> u16 {
+        match self {
+            WavFormat::Pcm16 => 1,
+            WavFormat::Float32 => 3,
+        }
+    }
+
+    fn bits_per_sample(self) -> u16 {
+        match self {
+            WavFormat::Pcm16 => 16,
+            WavFormat::Float32 => 32,
+        }
+    }
+}
+
+/// Streams the audio produced in the output callback to a WAV file, as either
+/// 16-bit PCM or 32-bit float depending on the chosen [`WavFormat`].
+///
+/// The audio thread taps the same interleaved `f32` buffer it hands to cpal and
+/// sends a copy over a channel (just like the visualiser's `audio_tx`), so the
+/// real-time path never touches the filesystem. A dedicated writer thread
+/// converts the samples and keeps the RIFF header's lengths patched up as it
+/// goes, leaving a playable file even if the process is killed.
+pub struct WavRecorder {
+    tx: Option<Sender<Vec<f32>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl WavRecorder {
+    pub fn new(
+        path: impl AsRef<Path>,
+        sample_rate: u32,
+        channels: u16,
+        format: WavFormat,
+    ) -> Result<WavRecorder> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_header(&mut writer, sample_rate, channels, format, 0)?;
+
+        let (tx, rx) = mpsc::channel::<Vec<f32>>();
+        let handle = thread::spawn(move || {
+            let mut data_len: u32 = 0;
+            while let Ok(buf) = rx.recv() {
+                for sample in buf {
+                    let bytes: [u8; 4] = match format {
+                        WavFormat::Pcm16 => {
+                            let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                            let b = pcm.to_le_bytes();
+                            [b[0], b[1], 0, 0]
+                        }
+                        WavFormat::Float32 => sample.to_le_bytes(),
+                    };
+                    let width = (format.bits_per_sample() / 8) as usize;
+                    if writer.write_all(&bytes[..width]).is_err() {
+                        return;
+                    }
+                    data_len += width as u32;
+                }
+
+                // backpatch the sizes so the file is valid at any point, then
+                // seek back to the end to continue appending
+                if patch_lengths(&mut writer, data_len).is_err() {
+                    return;
+                }
+            }
+
+            let _ = writer.flush();
+        });
+
+        Ok(WavRecorder {
+            tx: Some(tx),
+            handle: Some(handle),
+        })
+    }
+
+    /// A cloneable handle the audio thread uses to feed captured buffers in.
+    pub fn sender(&self) -> Sender<Vec<f32>> {
+        self.tx.clone().expect("recorder already finalized")
+    }
+}
+
+impl Drop for WavRecorder {
+    fn drop(&mut self) {
+        // dropping the sender closes the channel, letting the writer finish
+        drop(self.tx.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Write a canonical 44-byte WAV header for the given data length (in bytes).
+fn write_header<W: Write>(
+    w: &mut W,
+    sample_rate: u32,
+    channels: u16,
+    format: WavFormat,
+    data_len: u32,
+) -> Result<()> {
+    let bits_per_sample = format.bits_per_sample();
+    let block_align = channels * bits_per_sample / 8;
+    let byte_rate = sample_rate * block_align as u32;
+
+    w.write_all(b"RIFF")?;
+    w.write_all(&(36 + data_len).to_le_bytes())?;
+    w.write_all(b"WAVE")?;
+
+    w.write_all(b"fmt ")?;
+    w.write_all(&16u32.to_le_bytes())?;
+    w.write_all(&format.tag().to_le_bytes())?;
+    w.write_all(&channels.to_le_bytes())?;
+    w.write_all(&sample_rate.to_le_bytes())?;
+    w.write_all(&byte_rate.to_le_bytes())?;
+    w.write_all(&block_align.to_le_bytes())?;
+    w.write_all(&bits_per_sample.to_le_bytes())?;
+
+    w.write_all(b"data")?;
+    w.write_all(&data_len.to_le_bytes())?;
+
+    Ok(())
+}
+
+/// Patch the two length fields in an already-written header, then seek back to
+/// the end of the file ready for more samples.
+fn patch_lengths<W: Write + Seek>(w: &mut W, data_len: u32) -> Result<()> {
+    w.seek(SeekFrom::Start(4))?;
+    w.write_all(&(36 + data_len).to_le_bytes())?;
+    w.seek(SeekFrom::Start(40))?;
+    w.write_all(&data_len.to_le_bytes())?;
+    w.seek(SeekFrom::End(0))?;
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use anyhow::Result;
+
+/// Ticks per quarter note written into the `MThd` header. A higher value gives
+/// finer timing resolution for the recorded performance.
+const DIVISION: u16 = 480;
+/// Fixed tempo (beats per minute) used to convert elapsed milliseconds to ticks.
+const TEMPO_BPM: f32 = 120.0;
+
+/// A single note transition observed in `Notes::update_keys`, stamped with the
+/// wall-clock time it happened so the writer can derive MIDI delta times.
+pub struct MidiEvent {
+    at: Instant,
+    on: bool,
+    note: u8,
+    velocity: u8,
+}
+
+impl MidiEvent {
+    pub fn now(on: bool, note: u8, velocity: u8) -> MidiEvent {
+        MidiEvent {
+            at: Instant::now(),
+            on,
+            note,
+            velocity,
+        }
+    }
+}
+
+/// Logs note on/off events to a format-0 Standard MIDI File. Events are sent
+/// over a channel from the audio thread and serialised on a writer thread; the
+/// track is buffered in memory and written as one `MTrk` chunk, with its
+/// length, once recording stops.
+pub struct MidiRecorder {
+    tx: Option<Sender<MidiEvent>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MidiRecorder {
+    pub fn new(path: impl AsRef<Path>) -> Result<MidiRecorder> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_file_header(&mut writer)?;
+
+        let (tx, rx) = mpsc::channel::<MidiEvent>();
+        let handle = thread::spawn(move || {
+            let mut track = Vec::new();
+            let mut last: Option<Instant> = None;
+
+            while let Ok(event) = rx.recv() {
+                let delta_ms = match last {
+                    Some(prev) => event.at.duration_since(prev).as_millis() as f32,
+                    None => 0.0,
+                };
+                last = Some(event.at);
+
+                write_vlq(&mut track, ms_to_ticks(delta_ms));
+                if event.on {
+                    track.extend_from_slice(&[0x90, event.note & 0x7f, event.velocity & 0x7f]);
+                } else {
+                    track.extend_from_slice(&[0x80, event.note & 0x7f, 0x00]);
+                }
+            }
+
+            // end-of-track meta event, then the finished track chunk
+            write_vlq(&mut track, 0);
+            track.extend_from_slice(&[0xff, 0x2f, 0x00]);
+            let _ = write_track(&mut writer, &track);
+            let _ = writer.flush();
+        });
+
+        Ok(MidiRecorder {
+            tx: Some(tx),
+            handle: Some(handle),
+        })
+    }
+
+    /// A cloneable handle the audio thread uses to log note transitions.
+    pub fn sender(&self) -> Sender<MidiEvent> {
+        self.tx.clone().expect("recorder already finalized")
+    }
+}
+
+impl Drop for MidiRecorder {
+    fn drop(&mut self) {
+        drop(self.tx.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Convert a delta time in milliseconds to MIDI ticks at the fixed tempo.
+fn ms_to_ticks(delta_ms: f32) -> u32 {
+    let ticks_per_ms = DIVISION as f32 * TEMPO_BPM / 60_000.0;
+    (delta_ms * ticks_per_ms).round() as u32
+}
+
+/// Encode `value` as a MIDI variable-length quantity: 7 bits per byte, most
+/// significant first, with bit 7 set on every byte but the last.
+fn write_vlq(out: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push(((value & 0x7f) as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.reverse();
+    out.extend_from_slice(&bytes);
+}
+
+/// Write the `MThd` header: format 0, a single track, `DIVISION` ticks per
+/// quarter note.
+fn write_file_header<W: Write>(w: &mut W) -> Result<()> {
+    w.write_all(b"MThd")?;
+    w.write_all(&6u32.to_be_bytes())?;
+    w.write_all(&0u16.to_be_bytes())?; // format 0
+    w.write_all(&1u16.to_be_bytes())?; // one track
+    w.write_all(&DIVISION.to_be_bytes())?;
+    Ok(())
+}
+
+/// Write a complete `MTrk` chunk with the correct length prefix.
+fn write_track<W: Write + Seek>(w: &mut W, track: &[u8]) -> Result<()> {
+    w.seek(SeekFrom::End(0))?;
+    w.write_all(b"MTrk")?;
+    w.write_all(&(track.len() as u32).to_be_bytes())?;
+    w.write_all(track)?;
+    Ok(())
+}
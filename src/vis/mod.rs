@@ -1,3 +1,4 @@
+mod queue;
 mod renderer;
 
 use std::collections::VecDeque;
@@ -30,7 +31,8 @@ use winit::event::{
 use winit::event_loop::{ControlFlow, EventLoop, EventLoopBuilder};
 use winit::window::{Window, WindowBuilder};
 
-use crate::Action;
+use self::queue::TimedQueue;
+use crate::{Action, AudioChunk};
 
 const WINDOW_TITLE: &str = "keyboard-music";
 const WINDOW_X: i32 = 635;
@@ -133,6 +135,9 @@ pub struct VisualiserState {
     gl_context: Option<PossiblyCurrentContext>,
 
     paused: bool,
+    /// when true the renderer shows the frequency spectrum instead of the
+    /// time-domain waveform
+    spectrum: bool,
     vis_buffer_size: Arc<Mutex<usize>>,
 }
 
@@ -140,15 +145,20 @@ impl VisualiserState {
     fn toggle_pause(&mut self) {
         self.paused = !self.paused;
     }
+
+    fn toggle_spectrum(&mut self) {
+        self.spectrum = !self.spectrum;
+    }
 }
 
-pub fn open_and_run(audio_rx: Receiver<Vec<f32>>, wave_tx: Sender<Action>) -> ! {
+pub fn open_and_run(audio_rx: Receiver<AudioChunk>, wave_tx: Sender<Action>) -> ! {
     let audio_data = Arc::new(Mutex::new(VecDeque::with_capacity(VIS_BUFFER_DEFAULT)));
     let vis_buffer_size = Arc::new(Mutex::new(VIS_BUFFER_DEFAULT));
 
     let mut state = VisualiserState {
         gl_context: None,
         paused: false,
+        spectrum: false,
         vis_buffer_size: vis_buffer_size.clone(),
     };
 
@@ -158,12 +168,34 @@ pub fn open_and_run(audio_rx: Receiver<Vec<f32>>, wave_tx: Sender<Action>) -> !
         let audio_data = audio_data.clone();
         move || {
             let window = win_rx.recv().unwrap();
-            while let Ok(audio_buf) = audio_rx.recv() {
+            let mut incoming = TimedQueue::new();
+            while let Ok(chunk) = audio_rx.recv() {
+                // gather this chunk plus any that piled up while we were busy
+                incoming.push(chunk);
+                while let Ok(chunk) = audio_rx.try_recv() {
+                    incoming.push(chunk);
+                }
+
+                let buffer_size = usize::max(1, *vis_buffer_size.lock().unwrap());
                 let mut vec = audio_data.lock().unwrap();
-                vec.extend(audio_buf);
-                while vec.len() > usize::max(1, *vis_buffer_size.lock().unwrap()) {
+
+                if incoming.backlog() as usize > buffer_size {
+                    // we've fallen behind: snap to the newest audio rather than
+                    // trickling through a stale backlog
+                    vec.clear();
+                    if let Some(chunk) = incoming.pop_latest() {
+                        vec.extend(chunk.samples);
+                    }
+                } else {
+                    while let Some(chunk) = incoming.pop_next() {
+                        vec.extend(chunk.samples);
+                    }
+                }
+
+                while vec.len() > buffer_size {
                     vec.pop_front();
                 }
+                drop(vec);
 
                 window.request_redraw();
             }
@@ -212,6 +244,10 @@ pub fn open_and_run(audio_rx: Receiver<Vec<f32>>, wave_tx: Sender<Action>) -> !
                     Some(VirtualKeyCode::Space) if input.state == ElementState::Pressed => {
                         state.toggle_pause()
                     }
+                    // toggle between the waveform and spectrum views with F
+                    Some(VirtualKeyCode::F) if input.state == ElementState::Pressed => {
+                        state.toggle_spectrum()
+                    }
 
                     _ => {}
                 },
@@ -0,0 +1,49 @@
+use std::collections::VecDeque;
+
+use crate::AudioChunk;
+
+/// A FIFO of timestamped audio chunks sitting between the audio channel and the
+/// render buffer.
+///
+/// `pop_next` drains chunks in order, while `pop_latest` throws away everything
+/// but the most recent chunk so the renderer can snap back to live audio when
+/// redraws fall behind — otherwise the displayed waveform drifts further and
+/// further behind what's actually audible.
+#[derive(Default)]
+pub struct TimedQueue {
+    chunks: VecDeque<AudioChunk>,
+}
+
+impl TimedQueue {
+    pub fn new() -> TimedQueue {
+        TimedQueue::default()
+    }
+
+    pub fn push(&mut self, chunk: AudioChunk) {
+        self.chunks.push_back(chunk);
+    }
+
+    /// Number of samples spanned by the queued chunks, derived from the
+    /// sample-clock stamps so we can tell how far behind the renderer is.
+    pub fn backlog(&self) -> u64 {
+        match (self.chunks.front(), self.chunks.back()) {
+            (Some(front), Some(back)) => {
+                back.seq.saturating_sub(front.seq) + back.samples.len() as u64
+            }
+            _ => 0,
+        }
+    }
+
+    /// Pop the oldest chunk for in-order playback.
+    pub fn pop_next(&mut self) -> Option<AudioChunk> {
+        self.chunks.pop_front()
+    }
+
+    /// Discard all queued chunks but the newest and return it, realigning the
+    /// render window to the freshest audio.
+    pub fn pop_latest(&mut self) -> Option<AudioChunk> {
+        let latest = self.chunks.pop_back();
+        self.chunks.clear();
+        latest
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::VecDeque;
+use std::f32::consts::PI;
 use std::ffi::CString;
 use std::mem::size_of;
 use std::sync::{Arc, Mutex};
@@ -10,6 +11,11 @@ use glutin::prelude::*;
 use super::{VisualiserState, VIS_BUFFER_MAX, VIS_BUFFER_MIN};
 use crate::notes::{lerp, MAX_VOLUME};
 
+/// Size of the FFT analysis window; must be a power of two for the radix-2 FFT.
+const FFT_SIZE: usize = 2048;
+/// Floor of the decibel range mapped onto the vertical axis in spectrum mode.
+const SPECTRUM_FLOOR_DB: f32 = 90.0;
+
 /// Small helper to create (and set defaults) for uniforms
 enum UniformDefault {
     F32(f32),
@@ -40,11 +46,13 @@ pub struct Renderer {
 
     // (vao, vbo)
     vao_wave: (u32, u32),
+    vao_spectrum: (u32, u32),
     vao_zoom: (u32, u32),
     // (vao, count)
     vao_pause_icon: (u32, i32),
 
     wave_vertices: Vec<f32>,
+    spectrum_vertices: Vec<f32>,
 }
 
 impl Renderer {
@@ -104,6 +112,24 @@ impl Renderer {
                 (vao, vbo)
             };
 
+            /*
+             * Setup VAO for rendering the frequency spectrum
+             */
+
+            let vao_spectrum = {
+                let mut vao: GLuint = 0;
+                gl::GenVertexArrays(1, &mut vao);
+                gl::BindVertexArray(vao);
+
+                let mut vbo: GLuint = 0;
+                gl::GenBuffers(1, &mut vbo);
+                gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+                gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+                gl::EnableVertexAttribArray(0);
+
+                (vao, vbo)
+            };
+
             /*
              * Setup VAO for rendering zoom indicator
              */
@@ -211,10 +237,12 @@ impl Renderer {
                 u_is_drawing_wave,
 
                 vao_wave,
+                vao_spectrum,
                 vao_zoom,
                 vao_pause_icon,
 
                 wave_vertices: vec![0.0],
+                spectrum_vertices: vec![0.0],
             }
         }
     }
@@ -226,23 +254,50 @@ impl Renderer {
 
             if state.paused {
                 self.render_pause_icon();
+                self.render_zoom_indicator();
+                return;
+            }
+
+            // fetch and prepare audio data to be sent to gl
+            let audio_data = self.audio_data.lock().unwrap();
+            self.audio_data_len = audio_data.len() as f32;
+
+            if state.spectrum {
+                self.spectrum_vertices = compute_spectrum(&audio_data);
+                drop(audio_data);
+                self.render_spectrum();
             } else {
-                // fetch and prepare audio data to be sent to gl
-                let audio_data = self.audio_data.lock().unwrap();
-                self.audio_data_len = audio_data.len() as f32;
                 self.wave_vertices = audio_data
                     .iter()
                     .enumerate()
                     // we send the index and the audio value (y) over to the shader
                     .flat_map(|(idx, y)| vec![idx as f32, *y])
                     .collect();
-            };
+                drop(audio_data);
+                self.render_wave();
+            }
 
-            self.render_wave();
             self.render_zoom_indicator();
         }
     }
 
+    fn render_spectrum(&self) {
+        unsafe {
+            let (vao, vbo) = self.vao_spectrum;
+            // the vertices are already in clip space, so leave the wave path off
+            gl::Uniform1i(self.u_is_drawing_wave, 0);
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (self.spectrum_vertices.len() * size_of::<f32>()) as GLsizeiptr,
+                &self.spectrum_vertices[0] as *const f32 as *const GLvoid,
+                gl::STATIC_DRAW,
+            );
+            gl::DrawArrays(gl::LINE_STRIP, 0, self.spectrum_vertices.len() as i32 / 2);
+        }
+    }
+
     fn render_zoom_indicator(&self) {
         unsafe {
             // pairs of (x, y) coords
@@ -289,3 +344,117 @@ impl Renderer {
         }
     }
 }
+
+/// A minimal complex number for the FFT; avoids pulling in a dependency for the
+/// handful of operations we need.
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    fn add(self, o: Complex) -> Complex {
+        Complex {
+            re: self.re + o.re,
+            im: self.im + o.im,
+        }
+    }
+
+    fn sub(self, o: Complex) -> Complex {
+        Complex {
+            re: self.re - o.re,
+            im: self.im - o.im,
+        }
+    }
+
+    fn mul(self, o: Complex) -> Complex {
+        Complex {
+            re: self.re * o.re - self.im * o.im,
+            im: self.re * o.im + self.im * o.re,
+        }
+    }
+}
+
+/// Take the most recent `FFT_SIZE` samples, window them, run an FFT and map the
+/// magnitude spectrum onto a logarithmic frequency axis in clip space.
+fn compute_spectrum(audio_data: &VecDeque<f32>) -> Vec<f32> {
+    if audio_data.len() < FFT_SIZE {
+        return vec![0.0];
+    }
+
+    // most recent window, with a Hann window applied to reduce spectral leakage
+    let start = audio_data.len() - FFT_SIZE;
+    let mut buffer: Vec<Complex> = audio_data
+        .iter()
+        .skip(start)
+        .enumerate()
+        .map(|(n, &sample)| {
+            let window = 0.5 * (1.0 - (2.0 * PI * n as f32 / (FFT_SIZE as f32 - 1.0)).cos());
+            Complex {
+                re: sample * window,
+                im: 0.0,
+            }
+        })
+        .collect();
+
+    fft(&mut buffer);
+
+    let bins = FFT_SIZE / 2;
+    let log_max = (bins as f32).ln();
+    (0..bins)
+        .flat_map(|i| {
+            let magnitude = (buffer[i].re * buffer[i].re + buffer[i].im * buffer[i].im).sqrt();
+            let db = 20.0 * (magnitude + 1e-9).log10();
+
+            // logarithmic frequency axis: low notes get more horizontal room
+            let x = lerp(-1.0, 1.0, ((i + 1) as f32).ln() / log_max);
+            // map [-FLOOR, 0] dB onto [-1, 1]
+            let y = ((db + SPECTRUM_FLOOR_DB) / SPECTRUM_FLOOR_DB).clamp(0.0, 1.0) * 2.0 - 1.0;
+            [x, y]
+        })
+        .collect()
+}
+
+/// In-place iterative radix-2 Cooley-Tukey FFT. `input.len()` must be a power
+/// of two.
+fn fft(input: &mut [Complex]) {
+    let n = input.len();
+
+    // bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            input.swap(i, j);
+        }
+    }
+
+    // butterflies, doubling the transform length each pass
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        let wlen = Complex {
+            re: angle.cos(),
+            im: angle.sin(),
+        };
+        let mut i = 0;
+        while i < n {
+            let mut w = Complex { re: 1.0, im: 0.0 };
+            for k in 0..len / 2 {
+                let u = input[i + k];
+                let v = input[i + k + len / 2].mul(w);
+                input[i + k] = u.add(v);
+                input[i + k + len / 2] = u.sub(v);
+                w = w.mul(wlen);
+            }
+            i += len;
+        }
+        len <<= 1;
+    }
+}
@@ -0,0 +1,68 @@
+use super::phase::{poly_blep, Phase};
+use super::{WaveGenerator, TAU};
+
+/// Band-limited sawtooth: the naive ramp `2t - 1` with a PolyBLEP residual
+/// subtracted at the wrap discontinuity.
+pub struct BlepSawtooth {
+    phase: Phase,
+}
+
+impl WaveGenerator for BlepSawtooth {
+    fn new(sample_rate: f32) -> BlepSawtooth {
+        BlepSawtooth {
+            phase: Phase::new(sample_rate),
+        }
+    }
+
+    fn before(&mut self, rel_midi_note: i8) {
+        self.phase.before(rel_midi_note);
+    }
+
+    #[inline]
+    fn next(&mut self, sample_idx: f32) -> f32 {
+        let t = (self.phase.next(sample_idx) % TAU) / TAU;
+        let dt = self.phase.dt();
+        2.0 * t - 1.0 - poly_blep(t, dt)
+    }
+
+    fn after(&mut self, buf_len: f32) {
+        self.phase.after(buf_len);
+    }
+}
+
+/// Band-limited square: a naive square with PolyBLEP residuals added at the
+/// rising edge and subtracted at the falling edge (half a cycle later).
+pub struct BlepSquare {
+    phase: Phase,
+}
+
+impl BlepSquare {
+    const AMP: f32 = 0.5;
+}
+
+impl WaveGenerator for BlepSquare {
+    fn new(sample_rate: f32) -> BlepSquare {
+        BlepSquare {
+            phase: Phase::new(sample_rate),
+        }
+    }
+
+    fn before(&mut self, rel_midi_note: i8) {
+        self.phase.before(rel_midi_note);
+    }
+
+    #[inline]
+    fn next(&mut self, sample_idx: f32) -> f32 {
+        let t = (self.phase.next(sample_idx) % TAU) / TAU;
+        let dt = self.phase.dt();
+
+        let mut value = if t < 0.5 { 1.0 } else { -1.0 };
+        value += poly_blep(t, dt);
+        value -= poly_blep((t + 0.5) % 1.0, dt);
+        value * Self::AMP
+    }
+
+    fn after(&mut self, buf_len: f32) {
+        self.phase.after(buf_len);
+    }
+}
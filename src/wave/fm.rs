@@ -0,0 +1,83 @@
+use super::phase::Phase;
+use super::WaveGenerator;
+
+/// Serial FM synthesis: a stack of modulator sines phase-modulates a carrier
+/// sine. Each modulator runs at its own `ratio` times the carrier's frequency
+/// and bends the next stage's phase by its output scaled by `mod_index`, so the
+/// deepest modulator feeds the next, and so on up to the carrier. This "Genesis
+/// style" operator chain yields metallic/bell timbres the single-oscillator
+/// shapes can't.
+pub struct Fm {
+    carrier: Phase,
+    /// modulators ordered deepest-first, each paired with its modulation index
+    modulators: Vec<(Phase, f32)>,
+}
+
+impl Fm {
+    /// Default modulator:carrier ratio and modulation index, used when FM is
+    /// selected without explicit parameters.
+    const DEFAULT_RATIO: f32 = 2.0;
+    const DEFAULT_MOD_INDEX: f32 = 1.0;
+    /// Largest modulator stack, giving at most a 4-operator chain with the
+    /// carrier.
+    const MAX_MODULATORS: usize = 3;
+
+    /// Build an operator chain from the per-modulator `ratios` and
+    /// `mod_indices` (zipped, deepest modulator first). At most
+    /// `MAX_MODULATORS` pairs are used; with no modulators the carrier sounds
+    /// as a plain sine.
+    pub fn with_operators(sample_rate: f32, ratios: &[f32], mod_indices: &[f32]) -> Fm {
+        let modulators = ratios
+            .iter()
+            .zip(mod_indices)
+            .take(Fm::MAX_MODULATORS)
+            .map(|(&ratio, &mod_index)| (Phase::with_ratio(sample_rate, ratio), mod_index))
+            .collect();
+        Fm {
+            carrier: Phase::new(sample_rate),
+            modulators,
+        }
+    }
+}
+
+impl WaveGenerator for Fm {
+    fn new(sample_rate: f32) -> Fm {
+        Fm::with_operators(
+            sample_rate,
+            &[Fm::DEFAULT_RATIO],
+            &[Fm::DEFAULT_MOD_INDEX],
+        )
+    }
+
+    fn clear(&mut self, rel_midi_note: i8) {
+        self.carrier.clear(rel_midi_note);
+        for (phase, _) in &mut self.modulators {
+            phase.clear(rel_midi_note);
+        }
+    }
+
+    fn before(&mut self, rel_midi_note: i8) {
+        self.carrier.before(rel_midi_note);
+        for (phase, _) in &mut self.modulators {
+            phase.before(rel_midi_note);
+        }
+    }
+
+    #[inline]
+    fn next(&mut self, sample_idx: f32) -> f32 {
+        // walk the chain from the deepest modulator up, each stage offsetting
+        // the next stage's phase by its output scaled by the modulation index
+        let mut offset = 0.0;
+        for (phase, mod_index) in &mut self.modulators {
+            offset = (phase.next(sample_idx) + offset).sin() * *mod_index;
+        }
+        (self.carrier.next(sample_idx) + offset).sin()
+    }
+
+    fn after(&mut self, buf_len: f32) {
+        self.carrier.after(buf_len);
+        for (phase, _) in &mut self.modulators {
+            phase.after(buf_len);
+        }
+    }
+}
@@ -1,6 +1,9 @@
+mod blep;
+mod fm;
 mod phase;
 mod sawtooth;
 mod sine;
+pub mod soundfont;
 mod square;
 mod triangle;
 
@@ -25,15 +28,43 @@ pub enum Wave {
     Square,
     Triangle,
     Sawtooth,
+    Fm,
+}
+
+/// Tuning parameters for the FM generator, supplied from the CLI since the
+/// `Wave` enum itself stays a plain value-enum for `--wave`.
+///
+/// `ratios`/`mod_indices` describe the modulator stack feeding the carrier,
+/// deepest modulator first. Between one and three modulators are honoured (a
+/// 2–4 operator chain counting the carrier); the lists are zipped, so extra
+/// entries in either are ignored.
+#[derive(Debug, Clone)]
+pub struct FmConfig {
+    pub ratios: Vec<f32>,
+    pub mod_indices: Vec<f32>,
 }
 
 impl Wave {
-    pub fn generator(&self, sample_rate: f32) -> Box<dyn WaveGenerator> {
+    /// Build the oscillator-based generator for this wave. Sample-based
+    /// playback (`soundfont::SoundFont`) is constructed separately from a file
+    /// and injected into `Notes`, since it can't be expressed as a plain
+    /// value-enum variant.
+    pub fn generator(
+        &self,
+        sample_rate: f32,
+        fm: &FmConfig,
+        band_limited: bool,
+    ) -> Box<dyn WaveGenerator> {
         match self {
             Wave::Sine => Box::new(sine::Sine::new(sample_rate)),
+            // the square/sawtooth edges alias badly up high, so swap in the
+            // PolyBLEP versions when band-limiting is requested
+            Wave::Square if band_limited => Box::new(blep::BlepSquare::new(sample_rate)),
             Wave::Square => Box::new(square::Square::new(sample_rate)),
-            Wave::Triangle => Box::new(triangle::Triangle::new(sample_rate)),
+            Wave::Sawtooth if band_limited => Box::new(blep::BlepSawtooth::new(sample_rate)),
             Wave::Sawtooth => Box::new(sawtooth::Sawtooth::new(sample_rate)),
+            Wave::Triangle => Box::new(triangle::Triangle::new(sample_rate)),
+            Wave::Fm => Box::new(fm::Fm::with_operators(sample_rate, &fm.ratios, &fm.mod_indices)),
         }
     }
 }
@@ -11,13 +11,48 @@ pub struct Phase {
 
     base_factor: f32,
     wave_factor: f32,
+    /// multiplies the played note's frequency, letting FM operators run at a
+    /// fixed ratio above the carrier while staying phase-continuous per note
+    ratio: f32,
 }
 
 impl Phase {
+    /// Like `new`, but scales every note's frequency by `ratio`. Used to build
+    /// FM modulator oscillators that track the carrier at a fixed ratio.
+    pub fn with_ratio(sample_rate: f32, ratio: f32) -> Phase {
+        let mut phase = Phase::new(sample_rate);
+        phase.ratio = ratio;
+        phase
+    }
+
     #[inline]
     fn note_idx(&self, rel_midi_note: i8) -> usize {
         (rel_midi_note + i8::MAX) as usize
     }
+
+    /// Normalized per-sample phase increment (cycles per sample) for the note
+    /// set up by the last `before` call. Used by the PolyBLEP correction.
+    #[inline]
+    pub fn dt(&self) -> f32 {
+        self.wave_factor / TAU
+    }
+}
+
+/// PolyBLEP (polynomial band-limited step) residual used to smooth the hard
+/// discontinuities in naive sawtooth/square waves, killing most of their
+/// aliasing. `t` is the normalized phase in `[0, 1)` and `dt` the normalized
+/// per-sample increment.
+#[inline]
+pub fn poly_blep(mut t: f32, dt: f32) -> f32 {
+    if t < dt {
+        t /= dt;
+        t + t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        t = (t - 1.0) / dt;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
 }
 
 impl WaveGenerator for Phase {
@@ -30,6 +65,7 @@ impl WaveGenerator for Phase {
 
             base_factor: FREQ_FACTOR / sample_rate,
             wave_factor: 0.0,
+            ratio: 1.0,
         }
     }
 
@@ -40,7 +76,7 @@ impl WaveGenerator for Phase {
 
     #[inline]
     fn before(&mut self, rel_midi_note: i8) {
-        self.wave_factor = self.base_factor * PITCH_FACTOR.powf(rel_midi_note as f32);
+        self.wave_factor = self.base_factor * self.ratio * PITCH_FACTOR.powf(rel_midi_note as f32);
         self.current_phase = self.note_phases[self.note_idx(rel_midi_note)];
         self.current_note = rel_midi_note;
     }
@@ -0,0 +1,378 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+use super::WaveGenerator;
+use crate::notes::MIDI_OFFSET;
+
+// SoundFont generator operator numbers we care about (see the SF2 spec).
+const GEN_KEY_RANGE: u16 = 43;
+const GEN_OVERRIDING_ROOT_KEY: u16 = 58;
+const GEN_INSTRUMENT: u16 = 41;
+const GEN_SAMPLE_ID: u16 = 53;
+
+/// A single sample header from the `shdr` chunk, with all offsets in sample
+/// frames from the start of the `smpl` data.
+#[derive(Clone)]
+struct SampleHeader {
+    start: u32,
+    end: u32,
+    loop_start: u32,
+    loop_end: u32,
+    sample_rate: u32,
+    original_pitch: u8,
+}
+
+/// A key zone within the selected preset's instrument: the key range it covers
+/// and the sample (plus optional root-key override) it plays.
+#[derive(Clone)]
+struct Zone {
+    lo: u8,
+    hi: u8,
+    sample_id: usize,
+    root_override: Option<u8>,
+}
+
+/// A sample-based `WaveGenerator` backed by a SoundFont (SF2) file. For each
+/// played note it selects the matching sample zone, resamples it to the output
+/// rate and loops it within its loop points. The SF2-internal volume-envelope
+/// generators (`attackVolEnv`/`decayVolEnv`/… opers 34–38) are intentionally
+/// *not* parsed: amplitude shaping is left entirely to the synth's own ADSR
+/// (see `notes::Adsr`), which is multiplied in afterwards. This keeps the
+/// backend focused on pitch-correct, phase-continuous sample playback and gives
+/// every wave generator a single, consistent envelope driven from the CLI.
+pub struct SoundFont {
+    samples: Vec<i16>,
+    headers: Vec<SampleHeader>,
+    zones: Vec<Zone>,
+    output_rate: f32,
+
+    /// playback position (in sample frames from each sample's start) per note
+    note_positions: [f32; u8::MAX as usize],
+    current_note: i8,
+    current_pos: f32,
+    current_zone: Option<usize>,
+}
+
+impl SoundFont {
+    pub fn load(path: impl AsRef<Path>, preset: u16, output_rate: f32) -> Result<SoundFont> {
+        let bytes = fs::read(path.as_ref()).context("failed to read soundfont")?;
+        let sf2 = Sf2::parse(&bytes)?;
+        let zones = sf2.zones_for_preset(preset)?;
+
+        Ok(SoundFont {
+            samples: sf2.samples,
+            headers: sf2.headers,
+            zones,
+            output_rate,
+            note_positions: [0.0; u8::MAX as usize],
+            current_note: 0,
+            current_pos: 0.0,
+            current_zone: None,
+        })
+    }
+
+    #[inline]
+    fn note_idx(&self, rel_midi_note: i8) -> usize {
+        (rel_midi_note + i8::MAX) as usize
+    }
+
+    /// Find the zone covering a midi note, preferring the tightest match.
+    fn zone_for(&self, midi_note: u8) -> Option<usize> {
+        self.zones
+            .iter()
+            .enumerate()
+            .filter(|(_, z)| midi_note >= z.lo && midi_note <= z.hi)
+            .min_by_key(|(_, z)| z.hi - z.lo)
+            .map(|(idx, _)| idx)
+    }
+
+    /// Per-frame playback increment for the current zone: the pitch ratio from
+    /// the sample's root key combined with the sample-rate conversion.
+    fn step(&self, midi_note: u8, zone: &Zone) -> f32 {
+        let header = &self.headers[zone.sample_id];
+        let root = zone.root_override.unwrap_or(header.original_pitch);
+        let semitones = midi_note as f32 - root as f32;
+        let pitch = 2.0_f32.powf(semitones / 12.0);
+        pitch * header.sample_rate as f32 / self.output_rate
+    }
+
+    /// Read the sample at a fractional position (relative to the sample start)
+    /// with linear interpolation, wrapping within the loop points.
+    fn sample_at(&self, zone: &Zone, pos: f32) -> f32 {
+        let header = &self.headers[zone.sample_id];
+        let loop_start = (header.loop_start.saturating_sub(header.start)) as f32;
+        let loop_end = (header.loop_end.saturating_sub(header.start)) as f32;
+
+        let mut pos = pos;
+        if loop_end > loop_start {
+            while pos >= loop_end {
+                pos -= loop_end - loop_start;
+            }
+        }
+
+        let length = (header.end - header.start) as usize;
+        let i = pos.floor() as usize;
+        if i + 1 >= length {
+            return 0.0;
+        }
+
+        let base = header.start as usize;
+        let a = self.samples[base + i] as f32;
+        let b = self.samples[base + i + 1] as f32;
+        let frac = pos - i as f32;
+        (a + (b - a) * frac) / i16::MAX as f32
+    }
+}
+
+impl WaveGenerator for SoundFont {
+    fn new(_sample_rate: f32) -> Self {
+        // SoundFont needs a file to load; it's constructed via `load` instead,
+        // so this should never be reached through the normal `Wave::generator`.
+        unreachable!("SoundFont must be constructed with SoundFont::load")
+    }
+
+    fn clear(&mut self, rel_midi_note: i8) {
+        self.note_positions[self.note_idx(rel_midi_note)] = 0.0;
+    }
+
+    fn before(&mut self, rel_midi_note: i8) {
+        let midi_note = (rel_midi_note + MIDI_OFFSET) as u8;
+        self.current_zone = self.zone_for(midi_note);
+        self.current_note = rel_midi_note;
+        self.current_pos = self.note_positions[self.note_idx(rel_midi_note)];
+    }
+
+    #[inline]
+    fn next(&mut self, sample_idx: f32) -> f32 {
+        let Some(zone_id) = self.current_zone else {
+            return 0.0;
+        };
+        let zone = &self.zones[zone_id];
+        let midi_note = (self.current_note + MIDI_OFFSET) as u8;
+        let pos = self.current_pos + sample_idx * self.step(midi_note, zone);
+        self.sample_at(zone, pos)
+    }
+
+    fn after(&mut self, buf_len: f32) {
+        if let Some(zone_id) = self.current_zone {
+            let zone = &self.zones[zone_id];
+            let midi_note = (self.current_note + MIDI_OFFSET) as u8;
+            let pos = self.current_pos + buf_len * self.step(midi_note, zone);
+            // keep the stored position within the loop so it never runs off the
+            // end of the sample over a long held note
+            self.note_positions[self.note_idx(self.current_note)] =
+                self.wrapped_position(zone, pos);
+        }
+    }
+}
+
+impl SoundFont {
+    /// Wrap a position back into the loop region, mirroring `sample_at`.
+    fn wrapped_position(&self, zone: &Zone, pos: f32) -> f32 {
+        let header = &self.headers[zone.sample_id];
+        let loop_start = (header.loop_start.saturating_sub(header.start)) as f32;
+        let loop_end = (header.loop_end.saturating_sub(header.start)) as f32;
+        let mut pos = pos;
+        if loop_end > loop_start {
+            while pos >= loop_end {
+                pos -= loop_end - loop_start;
+            }
+        }
+        pos
+    }
+}
+
+/// A minimal SF2 parser: just enough of the RIFF structure to pull out the
+/// sample data and the preset -> instrument -> sample-zone mapping.
+struct Sf2 {
+    samples: Vec<i16>,
+    headers: Vec<SampleHeader>,
+
+    phdr: Vec<(u16, u16)>, // (preset number, preset bag index)
+    pbag: Vec<u16>,        // generator index per preset bag
+    pgen: Vec<(u16, u16)>, // (operator, amount)
+    inst: Vec<u16>,        // instrument bag index per instrument
+    ibag: Vec<u16>,        // generator index per instrument bag
+    igen: Vec<(u16, u16)>, // (operator, amount)
+}
+
+impl Sf2 {
+    fn parse(bytes: &[u8]) -> Result<Sf2> {
+        let chunks = Chunks::collect(bytes)?;
+
+        let smpl = chunks.get("smpl").ok_or(anyhow!("soundfont has no samples"))?;
+        let samples = smpl
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+
+        let headers = parse_records(chunks.get("shdr"), 46, |r| SampleHeader {
+            start: le_u32(&r[20..]),
+            end: le_u32(&r[24..]),
+            loop_start: le_u32(&r[28..]),
+            loop_end: le_u32(&r[32..]),
+            sample_rate: le_u32(&r[36..]),
+            original_pitch: r[40],
+        });
+
+        let phdr = parse_records(chunks.get("phdr"), 38, |r| (le_u16(&r[20..]), le_u16(&r[24..])));
+        let pbag = parse_records(chunks.get("pbag"), 4, |r| le_u16(&r[0..]));
+        let pgen = parse_records(chunks.get("pgen"), 4, |r| (le_u16(&r[0..]), le_u16(&r[2..])));
+        let inst = parse_records(chunks.get("inst"), 22, |r| le_u16(&r[20..]));
+        let ibag = parse_records(chunks.get("ibag"), 4, |r| le_u16(&r[0..]));
+        let igen = parse_records(chunks.get("igen"), 4, |r| (le_u16(&r[0..]), le_u16(&r[2..])));
+
+        Ok(Sf2 {
+            samples,
+            headers,
+            phdr,
+            pbag,
+            pgen,
+            inst,
+            ibag,
+            igen,
+        })
+    }
+
+    /// Resolve the sample zones for a preset number by walking preset -> bag ->
+    /// generators to the instrument, then the instrument's own zones.
+    fn zones_for_preset(&self, preset: u16) -> Result<Vec<Zone>> {
+        let preset_idx = self
+            .phdr
+            .iter()
+            .position(|(p, _)| *p == preset)
+            .ok_or_else(|| anyhow!("preset {} not found in soundfont", preset))?;
+
+        // preset bags for this preset (phdr terminates with a sentinel entry)
+        let bag_start = self.phdr[preset_idx].1 as usize;
+        let bag_end = self
+            .phdr
+            .get(preset_idx + 1)
+            .map(|(_, b)| *b as usize)
+            .unwrap_or(self.pbag.len());
+
+        let mut instrument = None;
+        for bag in bag_start..bag_end {
+            let gen_start = self.pbag[bag] as usize;
+            let gen_end = self.pbag.get(bag + 1).copied().unwrap_or(self.pgen.len() as u16) as usize;
+            for (oper, amount) in &self.pgen[gen_start..gen_end] {
+                if *oper == GEN_INSTRUMENT {
+                    instrument = Some(*amount as usize);
+                }
+            }
+        }
+        let instrument = instrument.ok_or(anyhow!("preset has no instrument"))?;
+
+        // instrument bags -> zones
+        let ibag_start = self.inst[instrument] as usize;
+        let ibag_end = self
+            .inst
+            .get(instrument + 1)
+            .map(|b| *b as usize)
+            .unwrap_or(self.ibag.len());
+
+        let mut zones = Vec::new();
+        for bag in ibag_start..ibag_end {
+            let gen_start = self.ibag[bag] as usize;
+            let gen_end = self.ibag.get(bag + 1).copied().unwrap_or(self.igen.len() as u16) as usize;
+
+            let (mut lo, mut hi) = (0u8, 127u8);
+            let mut root_override = None;
+            let mut sample_id = None;
+            for (oper, amount) in &self.igen[gen_start..gen_end] {
+                match *oper {
+                    GEN_KEY_RANGE => {
+                        lo = (*amount & 0xff) as u8;
+                        hi = (*amount >> 8) as u8;
+                    }
+                    GEN_OVERRIDING_ROOT_KEY => root_override = Some(*amount as u8),
+                    GEN_SAMPLE_ID => sample_id = Some(*amount as usize),
+                    _ => {}
+                }
+            }
+
+            if let Some(sample_id) = sample_id {
+                zones.push(Zone {
+                    lo,
+                    hi,
+                    sample_id,
+                    root_override,
+                });
+            }
+        }
+
+        if zones.is_empty() {
+            return Err(anyhow!("preset {} has no playable sample zones", preset));
+        }
+
+        Ok(zones)
+    }
+}
+
+/// Flattened view of the chunks that live inside the `sdta` and `pdta` lists.
+struct Chunks<'a> {
+    named: Vec<(&'a str, &'a [u8])>,
+}
+
+impl<'a> Chunks<'a> {
+    fn collect(bytes: &'a [u8]) -> Result<Chunks<'a>> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"sfbk" {
+            return Err(anyhow!("not a SoundFont (sfbk) file"));
+        }
+
+        let mut named = Vec::new();
+        let mut walk = |data: &'a [u8]| {
+            let mut pos = 0;
+            while pos + 8 <= data.len() {
+                let id = std::str::from_utf8(&data[pos..pos + 4]).unwrap_or("");
+                let size = le_u32(&data[pos + 4..]) as usize;
+                let body = &data[pos + 8..(pos + 8 + size).min(data.len())];
+                named.push((id, body));
+                // chunks are word-aligned
+                pos += 8 + size + (size & 1);
+            }
+        };
+
+        // top-level RIFF body is a sequence of LIST chunks
+        let mut pos = 12;
+        while pos + 8 <= bytes.len() {
+            let id = &bytes[pos..pos + 4];
+            let size = le_u32(&bytes[pos + 4..]) as usize;
+            let body = &bytes[pos + 8..(pos + 8 + size).min(bytes.len())];
+            if id == b"LIST" && body.len() >= 4 {
+                walk(&body[4..]);
+            }
+            pos += 8 + size + (size & 1);
+        }
+
+        Ok(Chunks { named })
+    }
+
+    fn get(&self, id: &str) -> Option<&'a [u8]> {
+        self.named.iter().find(|(n, _)| *n == id).map(|(_, b)| *b)
+    }
+}
+
+/// Split a fixed-record chunk into parsed records, deliberately retaining the
+/// spec-mandated terminal sentinel record: the zone resolution above computes a
+/// bag's end index from the *next* record (`phdr.get(i + 1)`, `inst.get(i + 1)`,
+/// `pbag.get(i + 1)`), so the sentinel must be present for the last real record's
+/// range to be bounded. Dropping it would silently corrupt that lookup.
+fn parse_records<T>(chunk: Option<&[u8]>, record_size: usize, parse: impl Fn(&[u8]) -> T) -> Vec<T> {
+    let Some(data) = chunk else {
+        return Vec::new();
+    };
+    data.chunks_exact(record_size).map(parse).collect()
+}
+
+#[inline]
+fn le_u16(b: &[u8]) -> u16 {
+    u16::from_le_bytes([b[0], b[1]])
+}
+
+#[inline]
+fn le_u32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}